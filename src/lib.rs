@@ -1,28 +1,152 @@
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+#[cfg(feature = "process-metrics")]
+use std::time::Duration;
+
+#[cfg(feature = "process-metrics")]
+mod process;
+
+#[cfg(feature = "process-metrics")]
+use process::ProcessMetrics;
 
 #[cfg(feature = "actix")]
 use actix_web::{
-    body::MessageBody,
+    body::{BodySize, MessageBody},
     dev::{Service, ServiceRequest, ServiceResponse, Transform},
-    http::{Method, StatusCode},
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH},
+        Method, StatusCode,
+    },
     web::Data,
-    Error,
+    Error, HttpResponse,
 };
 #[cfg(feature = "actix")]
 use actix_web_lab::middleware::{from_fn, Next};
 
+#[cfg(feature = "actix")]
+mod normalize;
+
+#[cfg(feature = "actix")]
+pub use normalize::HandlerNormalizer;
+
 use prometheus::{
-    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder,
 };
 
 const DEFAULT_BUCKETS: [f64; 14] = [
     0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0,
 ];
 
+const DEFAULT_SIZE_BUCKETS: [f64; 11] = [
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0, 4194304.0, 16777216.0,
+    67108864.0,
+];
+
+/// Names, help texts and labels used when registering the HTTP metrics.
+pub struct MetricsConfiguration {
+    pub http_requests_total_name: String,
+    pub http_requests_total_help: String,
+    pub http_requests_duration_seconds_name: String,
+    pub http_requests_duration_seconds_help: String,
+    pub http_requests_in_flight_name: String,
+    pub http_requests_in_flight_help: String,
+    pub http_request_size_bytes_name: String,
+    pub http_request_size_bytes_help: String,
+    pub http_response_size_bytes_name: String,
+    pub http_response_size_bytes_help: String,
+    pub method_label: String,
+    pub handler_label: String,
+    pub code_label: String,
+    pub const_labels: HashMap<String, String>,
+    #[cfg(feature = "process-metrics")]
+    pub process_resident_memory_bytes_name: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_resident_memory_bytes_help: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_virtual_memory_bytes_name: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_virtual_memory_bytes_help: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_cpu_usage_percent_name: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_cpu_usage_percent_help: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_open_fds_name: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_open_fds_help: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_uptime_seconds_name: String,
+    #[cfg(feature = "process-metrics")]
+    pub process_uptime_seconds_help: String,
+}
+
+impl MetricsConfiguration {
+    fn label_names(&self) -> [String; 3] {
+        [
+            self.method_label.clone(),
+            self.handler_label.clone(),
+            self.code_label.clone(),
+        ]
+    }
+}
+
+impl Default for MetricsConfiguration {
+    fn default() -> Self {
+        Self {
+            http_requests_total_name: "http_requests_total".to_string(),
+            http_requests_total_help: "Total number of HTTP requests".to_string(),
+            http_requests_duration_seconds_name: "http_requests_duration_seconds".to_string(),
+            http_requests_duration_seconds_help:
+                "HTTP request duration in seconds for all requests".to_string(),
+            http_requests_in_flight_name: "http_requests_in_flight".to_string(),
+            http_requests_in_flight_help: "Number of HTTP requests currently being processed"
+                .to_string(),
+            http_request_size_bytes_name: "http_request_size_bytes".to_string(),
+            http_request_size_bytes_help: "HTTP request body size in bytes for all requests"
+                .to_string(),
+            http_response_size_bytes_name: "http_response_size_bytes".to_string(),
+            http_response_size_bytes_help: "HTTP response body size in bytes for all requests"
+                .to_string(),
+            method_label: "method".to_string(),
+            handler_label: "handler".to_string(),
+            code_label: "code".to_string(),
+            const_labels: HashMap::new(),
+            #[cfg(feature = "process-metrics")]
+            process_resident_memory_bytes_name: "process_resident_memory_bytes".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_resident_memory_bytes_help: "Resident memory size in bytes".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_virtual_memory_bytes_name: "process_virtual_memory_bytes".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_virtual_memory_bytes_help: "Virtual memory size in bytes".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_cpu_usage_percent_name: "process_cpu_usage_percent".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_cpu_usage_percent_help: "Process CPU usage percentage".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_open_fds_name: "process_open_fds".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_open_fds_help: "Number of open file descriptors".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_uptime_seconds_name: "process_uptime_seconds".to_string(),
+            #[cfg(feature = "process-metrics")]
+            process_uptime_seconds_help: "Process uptime in seconds".to_string(),
+        }
+    }
+}
+
 pub struct HttpMetricsCollectorBuilder {
     registry: Registry,
     endpoint: Option<String>,
     buckets: Vec<f64>,
+    configuration: MetricsConfiguration,
+    on_collect: Option<Box<dyn Fn() + Send + Sync>>,
+    #[cfg(feature = "actix")]
+    metrics_guard: Option<Box<dyn Fn(&ServiceRequest) -> bool + Send + Sync>>,
+    #[cfg(feature = "actix")]
+    handler_normalizer: Option<HandlerNormalizer>,
+    #[cfg(feature = "process-metrics")]
+    process_metrics_refresh_interval: Duration,
 }
 
 impl HttpMetricsCollectorBuilder {
@@ -31,6 +155,14 @@ impl HttpMetricsCollectorBuilder {
             endpoint: None,
             buckets: DEFAULT_BUCKETS.to_vec(),
             registry: Registry::new(),
+            configuration: MetricsConfiguration::default(),
+            on_collect: None,
+            #[cfg(feature = "actix")]
+            metrics_guard: None,
+            #[cfg(feature = "actix")]
+            handler_normalizer: None,
+            #[cfg(feature = "process-metrics")]
+            process_metrics_refresh_interval: Duration::ZERO,
         }
     }
 
@@ -49,36 +181,145 @@ impl HttpMetricsCollectorBuilder {
         self
     }
 
+    pub fn configuration(mut self, configuration: MetricsConfiguration) -> Self {
+        self.configuration = configuration;
+        self
+    }
+
+    /// Callback invoked at the start of every `collect()`, before the
+    /// registry is gathered.
+    pub fn on_collect<F>(mut self, callback: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.on_collect = Some(Box::new(callback));
+        self
+    }
+
+    /// Guard evaluated before the metrics endpoint is served. Requests
+    /// are rejected with `401 Unauthorized` when it returns `false`.
+    #[cfg(feature = "actix")]
+    pub fn metrics_guard<F>(mut self, guard: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + Send + Sync + 'static,
+    {
+        self.metrics_guard = Some(Box::new(guard));
+        self
+    }
+
+    /// Normalizer applied to the `handler` label before it is attached
+    /// to a metric.
+    #[cfg(feature = "actix")]
+    pub fn handler_normalizer(mut self, normalizer: HandlerNormalizer) -> Self {
+        self.handler_normalizer = Some(normalizer);
+        self
+    }
+
+    /// Minimum time between two refreshes of the process/host gauges.
+    /// Defaults to zero, which refreshes them on every scrape. Note that
+    /// `process_cpu_usage_percent` needs at least `sysinfo`'s internal
+    /// ~200ms sampling window between two refreshes to report a real
+    /// value; scraping faster than that reads `0` or a stale value.
+    #[cfg(feature = "process-metrics")]
+    pub fn process_metrics_refresh_interval(mut self, interval: Duration) -> Self {
+        self.process_metrics_refresh_interval = interval;
+        self
+    }
+
     pub fn build(self) -> HttpMetricsCollector {
-        let http_requests_total_opts =
-            Opts::new("http_requests_total", "Total number of HTTP requests");
+        let label_names = self.configuration.label_names();
 
-        let label_names = ["method", "handler", "code"];
+        let http_requests_total_opts = Opts::new(
+            self.configuration.http_requests_total_name.clone(),
+            self.configuration.http_requests_total_help.clone(),
+        )
+        .const_labels(self.configuration.const_labels.clone());
 
         let http_requests_total =
             IntCounterVec::new(http_requests_total_opts, &label_names).unwrap();
 
         let http_requests_duration_seconds_opts = HistogramOpts::new(
-            "http_requests_duration_seconds",
-            "HTTP request duration in seconds for all requests",
+            self.configuration.http_requests_duration_seconds_name.clone(),
+            self.configuration.http_requests_duration_seconds_help.clone(),
         )
-        .buckets(self.buckets);
+        .buckets(self.buckets)
+        .const_labels(self.configuration.const_labels.clone());
 
         let http_requests_duration_seconds =
             HistogramVec::new(http_requests_duration_seconds_opts, &label_names).unwrap();
 
+        let http_requests_in_flight_opts = Opts::new(
+            self.configuration.http_requests_in_flight_name.clone(),
+            self.configuration.http_requests_in_flight_help.clone(),
+        )
+        .const_labels(self.configuration.const_labels.clone());
+
+        let in_flight_label_names = [
+            self.configuration.method_label.clone(),
+            self.configuration.handler_label.clone(),
+        ];
+
+        let http_requests_in_flight =
+            IntGaugeVec::new(http_requests_in_flight_opts, &in_flight_label_names).unwrap();
+
+        let http_request_size_bytes_opts = HistogramOpts::new(
+            self.configuration.http_request_size_bytes_name.clone(),
+            self.configuration.http_request_size_bytes_help.clone(),
+        )
+        .buckets(DEFAULT_SIZE_BUCKETS.to_vec())
+        .const_labels(self.configuration.const_labels.clone());
+
+        let http_request_size_bytes =
+            HistogramVec::new(http_request_size_bytes_opts, &label_names).unwrap();
+
+        let http_response_size_bytes_opts = HistogramOpts::new(
+            self.configuration.http_response_size_bytes_name.clone(),
+            self.configuration.http_response_size_bytes_help.clone(),
+        )
+        .buckets(DEFAULT_SIZE_BUCKETS.to_vec())
+        .const_labels(self.configuration.const_labels.clone());
+
+        let http_response_size_bytes =
+            HistogramVec::new(http_response_size_bytes_opts, &label_names).unwrap();
+
         self.registry
             .register(Box::new(http_requests_total.clone()))
             .unwrap();
         self.registry
             .register(Box::new(http_requests_duration_seconds.clone()))
             .unwrap();
+        self.registry
+            .register(Box::new(http_requests_in_flight.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(http_request_size_bytes.clone()))
+            .unwrap();
+        self.registry
+            .register(Box::new(http_response_size_bytes.clone()))
+            .unwrap();
+
+        #[cfg(feature = "process-metrics")]
+        let process_metrics = ProcessMetrics::new(
+            &self.registry,
+            &self.configuration,
+            self.process_metrics_refresh_interval,
+        );
 
         HttpMetricsCollector {
             registry: self.registry,
             http_requests_duration_seconds,
             http_requests_total,
+            http_requests_in_flight,
+            http_request_size_bytes,
+            http_response_size_bytes,
             endpoint: self.endpoint.unwrap_or("/metrics".to_string()),
+            on_collect: self.on_collect,
+            #[cfg(feature = "actix")]
+            metrics_guard: self.metrics_guard,
+            #[cfg(feature = "actix")]
+            handler_normalizer: self.handler_normalizer,
+            #[cfg(feature = "process-metrics")]
+            process_metrics,
         }
     }
 }
@@ -93,16 +334,29 @@ pub struct HttpMetricsCollector {
     registry: Registry,
     http_requests_total: IntCounterVec,
     http_requests_duration_seconds: HistogramVec,
+    http_requests_in_flight: IntGaugeVec,
+    http_request_size_bytes: HistogramVec,
+    http_response_size_bytes: HistogramVec,
     endpoint: String,
+    on_collect: Option<Box<dyn Fn() + Send + Sync>>,
+    #[cfg(feature = "actix")]
+    metrics_guard: Option<Box<dyn Fn(&ServiceRequest) -> bool + Send + Sync>>,
+    #[cfg(feature = "actix")]
+    handler_normalizer: Option<HandlerNormalizer>,
+    #[cfg(feature = "process-metrics")]
+    process_metrics: ProcessMetrics,
 }
 
 impl HttpMetricsCollector {
+    #[allow(clippy::too_many_arguments)]
     pub fn update_metrics(
         &self,
         method: &Method,
         handler: &str,
         code: StatusCode,
         timestamp: Instant,
+        request_size: Option<u64>,
+        response_size: Option<u64>,
     ) {
         let label_values = [method.as_str(), handler, code.as_str()];
 
@@ -117,9 +371,40 @@ impl HttpMetricsCollector {
         self.http_requests_total
             .with_label_values(&label_values)
             .inc();
+
+        if let Some(size) = request_size {
+            self.http_request_size_bytes
+                .with_label_values(&label_values)
+                .observe(size as f64);
+        }
+
+        if let Some(size) = response_size {
+            self.http_response_size_bytes
+                .with_label_values(&label_values)
+                .observe(size as f64);
+        }
+    }
+
+    pub fn inc_in_flight(&self, method: &Method, handler: &str) {
+        self.http_requests_in_flight
+            .with_label_values(&[method.as_str(), handler])
+            .inc();
+    }
+
+    pub fn dec_in_flight(&self, method: &Method, handler: &str) {
+        self.http_requests_in_flight
+            .with_label_values(&[method.as_str(), handler])
+            .dec();
     }
 
     pub fn collect(&self) -> Result<String, String> {
+        if let Some(callback) = &self.on_collect {
+            callback();
+        }
+
+        #[cfg(feature = "process-metrics")]
+        self.process_metrics.refresh();
+
         let encoder = TextEncoder::new();
         let mut buffer = vec![];
 
@@ -136,6 +421,22 @@ impl HttpMetricsCollector {
     pub fn is_endpoint(&self, path: &str, method: &Method) -> bool {
         path == self.endpoint && method == Method::GET
     }
+
+    #[cfg(feature = "actix")]
+    pub fn is_authorized(&self, req: &ServiceRequest) -> bool {
+        match &self.metrics_guard {
+            Some(guard) => guard(req),
+            None => true,
+        }
+    }
+
+    #[cfg(feature = "actix")]
+    pub fn normalize_handler(&self, handler: &str) -> String {
+        match &self.handler_normalizer {
+            Some(normalizer) => normalizer.normalize(handler),
+            None => handler.to_string(),
+        }
+    }
 }
 
 struct MetricLog {
@@ -144,15 +445,33 @@ struct MetricLog {
     method: Method,
     code: StatusCode,
     timestamp: Instant,
+    in_flight: bool,
+    request_size: Option<u64>,
+    response_size: Option<u64>,
 }
 
 impl Drop for MetricLog {
     fn drop(&mut self) {
-        self.collector
-            .update_metrics(&self.method, &self.handler, self.code, self.timestamp)
+        self.collector.update_metrics(
+            &self.method,
+            &self.handler,
+            self.code,
+            self.timestamp,
+            self.request_size,
+            self.response_size,
+        );
+
+        if self.in_flight {
+            self.collector.dec_in_flight(&self.method, &self.handler);
+        }
     }
 }
 
+#[cfg(feature = "actix")]
+fn content_length(header: Option<&HeaderValue>) -> Option<u64> {
+    header?.to_str().ok()?.parse().ok()
+}
+
 #[cfg(feature = "actix")]
 pub fn metrics<S, B>() -> impl Transform<
     S,
@@ -186,6 +505,10 @@ where
             }
         };
 
+        let handler = collector.normalize_handler(&handler);
+
+        let request_size = content_length(req.headers().get(CONTENT_LENGTH));
+
         async move {
             let mut log = MetricLog {
                 collector: collector.clone().into_inner(),
@@ -193,17 +516,33 @@ where
                 timestamp,
                 code: StatusCode::OK,
                 handler,
+                in_flight: false,
+                request_size,
+                response_size: None,
             };
 
             if collector.is_endpoint(req.path(), req.method()) {
-                Ok(req
-                    .into_response(collector.collect().unwrap())
-                    .map_into_right_body())
+                if collector.is_authorized(&req) {
+                    Ok(req
+                        .into_response(collector.collect().unwrap())
+                        .map_into_right_body())
+                } else {
+                    log.code = StatusCode::UNAUTHORIZED;
+                    Ok(req
+                        .into_response(HttpResponse::Unauthorized().finish())
+                        .map_into_right_body())
+                }
             } else {
+                collector.inc_in_flight(&log.method, &log.handler);
+                log.in_flight = true;
+
                 match next.call(req).await {
                     Ok(res) => {
                         let status = res.status();
                         log.code = status;
+                        if let BodySize::Sized(size) = res.response().body().size() {
+                            log.response_size = Some(size);
+                        }
                         Ok(res.map_into_left_body())
                     }
                     Err(err) => {
@@ -216,3 +555,183 @@ where
         }
     })
 }
+
+#[cfg(all(test, feature = "actix"))]
+mod tests {
+    use actix_web::{test, web, App};
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    fn in_flight_values(metrics: &str) -> Vec<i64> {
+        metrics
+            .lines()
+            .filter(|line| line.starts_with("http_requests_in_flight{"))
+            .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn in_flight_returns_to_zero_after_success_and_error() {
+        let collector = web::Data::new(HttpMetricsCollectorBuilder::new().build());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(collector.clone())
+                .wrap(metrics())
+                .route("/ok", web::get().to(|| async { HttpResponse::Ok().finish() }))
+                .route(
+                    "/err",
+                    web::get().to(|| async {
+                        Err::<HttpResponse, Error>(actix_web::error::ErrorInternalServerError(
+                            "boom",
+                        ))
+                    }),
+                ),
+        )
+        .await;
+
+        test::call_service(&app, TestRequest::get().uri("/ok").to_request()).await;
+        for value in in_flight_values(&collector.collect().unwrap()) {
+            assert_eq!(value, 0);
+        }
+
+        let _ = app.call(TestRequest::get().uri("/err").to_request()).await;
+        for value in in_flight_values(&collector.collect().unwrap()) {
+            assert_eq!(value, 0);
+        }
+    }
+
+    #[test]
+    fn metrics_guard_defaults_to_allow_all() {
+        let collector = HttpMetricsCollectorBuilder::new().build();
+        let req = TestRequest::default().to_srv_request();
+
+        assert!(collector.is_authorized(&req));
+    }
+
+    #[test]
+    fn metrics_guard_rejects_when_guard_returns_false() {
+        let collector = HttpMetricsCollectorBuilder::new()
+            .metrics_guard(|_req| false)
+            .build();
+        let req = TestRequest::default().to_srv_request();
+
+        assert!(!collector.is_authorized(&req));
+    }
+
+    #[test]
+    fn metrics_guard_allows_when_guard_returns_true() {
+        let collector = HttpMetricsCollectorBuilder::new()
+            .metrics_guard(|_req| true)
+            .build();
+        let req = TestRequest::default().to_srv_request();
+
+        assert!(collector.is_authorized(&req));
+    }
+
+    #[test]
+    fn content_length_missing_header_is_none() {
+        assert_eq!(content_length(None), None);
+    }
+
+    #[test]
+    fn content_length_non_numeric_is_none() {
+        let header = HeaderValue::from_static("not-a-number");
+
+        assert_eq!(content_length(Some(&header)), None);
+    }
+
+    #[test]
+    fn content_length_non_utf8_is_none() {
+        let header = HeaderValue::from_bytes(&[0xff, 0xfe]).unwrap();
+
+        assert_eq!(content_length(Some(&header)), None);
+    }
+
+    #[test]
+    fn content_length_parses_valid_value() {
+        let header = HeaderValue::from_static("42");
+
+        assert_eq!(content_length(Some(&header)), Some(42));
+    }
+
+    fn metric_sum(metrics: &str, name: &str) -> f64 {
+        metrics
+            .lines()
+            .find(|line| line.starts_with(&format!("{name}_sum")))
+            .unwrap()
+            .rsplit(' ')
+            .next()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn update_metrics_records_distinct_request_and_response_sizes() {
+        let collector = HttpMetricsCollectorBuilder::new().build();
+
+        collector.update_metrics(
+            &Method::GET,
+            "/items",
+            StatusCode::OK,
+            Instant::now(),
+            Some(100),
+            Some(200),
+        );
+
+        let metrics = collector.collect().unwrap();
+
+        assert_eq!(metric_sum(&metrics, "http_request_size_bytes"), 100.0);
+        assert_eq!(metric_sum(&metrics, "http_response_size_bytes"), 200.0);
+    }
+
+    #[test]
+    fn custom_configuration_names_and_labels_appear_in_output() {
+        let mut const_labels = HashMap::new();
+        const_labels.insert("service".to_string(), "orders".to_string());
+
+        let configuration = MetricsConfiguration {
+            http_requests_total_name: "svc_requests_total".to_string(),
+            http_requests_total_help: "Custom help text".to_string(),
+            const_labels,
+            ..MetricsConfiguration::default()
+        };
+
+        let collector = HttpMetricsCollectorBuilder::new()
+            .configuration(configuration)
+            .build();
+
+        collector.update_metrics(
+            &Method::GET,
+            "/items",
+            StatusCode::OK,
+            Instant::now(),
+            None,
+            None,
+        );
+
+        let metrics = collector.collect().unwrap();
+
+        assert!(metrics.contains("svc_requests_total"));
+        assert!(metrics.contains("Custom help text"));
+        assert!(metrics.contains("service=\"orders\""));
+    }
+
+    #[test]
+    fn on_collect_callback_fires_during_collect() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let called = Arc::new(AtomicBool::new(false));
+        let called_in_callback = called.clone();
+
+        let collector = HttpMetricsCollectorBuilder::new()
+            .on_collect(move || called_in_callback.store(true, Ordering::SeqCst))
+            .build();
+
+        collector.collect().unwrap();
+
+        assert!(called.load(Ordering::SeqCst));
+    }
+}
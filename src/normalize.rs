@@ -0,0 +1,110 @@
+use std::collections::HashSet;
+
+use regex::Regex;
+
+/// Maps handlers matching `pattern` to a stable `label`.
+struct HandlerRule {
+    pattern: Regex,
+    label: String,
+}
+
+/// Normalizes the `handler` label before it is attached to a metric.
+/// Rules are tried in registration order; the first match wins. If an
+/// allowlist was configured, the resulting label (rule output or raw
+/// handler) is checked against it; anything outside of it collapses
+/// into the `"*"` bucket.
+#[derive(Default)]
+pub struct HandlerNormalizer {
+    rules: Vec<HandlerRule>,
+    allowlist: Option<HashSet<String>>,
+}
+
+impl HandlerNormalizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports handlers matching `pattern` under `label` instead of
+    /// their raw value.
+    pub fn rule(mut self, pattern: &str, label: &str) -> Self {
+        self.rules.push(HandlerRule {
+            pattern: Regex::new(pattern).unwrap(),
+            label: label.to_string(),
+        });
+        self
+    }
+
+    /// Restricts emitted series to this set of handler labels; anything
+    /// else (after rules are applied) folds into `"*"`.
+    pub fn allowlist<I, S>(mut self, handlers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.allowlist = Some(handlers.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn normalize(&self, handler: &str) -> String {
+        for rule in &self.rules {
+            if rule.pattern.is_match(handler) {
+                return self.apply_allowlist(&rule.label);
+            }
+        }
+
+        self.apply_allowlist(handler)
+    }
+
+    fn apply_allowlist(&self, label: &str) -> String {
+        match &self.allowlist {
+            Some(allowlist) if !allowlist.contains(label) => "*".to_string(),
+            _ => label.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passthrough_with_no_rules_or_allowlist() {
+        let normalizer = HandlerNormalizer::new();
+
+        assert_eq!(normalizer.normalize("/users/42"), "/users/42");
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let normalizer = HandlerNormalizer::new()
+            .rule(r"^/users/\d+$", "/users/{id}")
+            .rule(r"^/users/", "/users/*");
+
+        assert_eq!(normalizer.normalize("/users/42"), "/users/{id}");
+    }
+
+    #[test]
+    fn unmatched_rule_output_outside_allowlist_folds_to_star() {
+        let normalizer = HandlerNormalizer::new()
+            .rule(r"^/users/\d+$", "/users/{id}")
+            .allowlist(["/health"]);
+
+        assert_eq!(normalizer.normalize("/users/42"), "*");
+    }
+
+    #[test]
+    fn handler_in_allowlist_passes_through() {
+        let normalizer = HandlerNormalizer::new().allowlist(["/health"]);
+
+        assert_eq!(normalizer.normalize("/health"), "/health");
+    }
+
+    #[test]
+    fn rule_output_in_allowlist_passes_through() {
+        let normalizer = HandlerNormalizer::new()
+            .rule(r"^/users/\d+$", "/users/{id}")
+            .allowlist(["/users/{id}"]);
+
+        assert_eq!(normalizer.normalize("/users/42"), "/users/{id}");
+    }
+}
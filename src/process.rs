@@ -0,0 +1,185 @@
+use std::{sync::Mutex, time::Duration, time::Instant};
+
+use prometheus::{Gauge, IntGauge, Opts, Registry};
+use sysinfo::{Pid, System};
+
+use crate::MetricsConfiguration;
+
+/// Process/host gauges sampled lazily at scrape time via `sysinfo`.
+/// There is no background thread: [`ProcessMetrics::refresh`] is called
+/// from `collect()` right before the registry is gathered.
+///
+/// `refresh_interval` throttles that work across fast scrapes. Note
+/// that `cpu_usage_percent` relies on `sysinfo`'s internal minimum
+/// sampling window (~200ms) between two refreshes to produce a real
+/// delta; scraping faster than that reads `0` or a stale value.
+pub struct ProcessMetrics {
+    system: Mutex<System>,
+    pid: Pid,
+    refresh_interval: Duration,
+    last_refresh: Mutex<Option<Instant>>,
+    resident_memory_bytes: IntGauge,
+    virtual_memory_bytes: IntGauge,
+    cpu_usage_percent: Gauge,
+    open_fds: IntGauge,
+    uptime_seconds: IntGauge,
+}
+
+impl ProcessMetrics {
+    pub fn new(
+        registry: &Registry,
+        configuration: &MetricsConfiguration,
+        refresh_interval: Duration,
+    ) -> Self {
+        let pid = Pid::from_u32(std::process::id());
+
+        let resident_memory_bytes = IntGauge::with_opts(
+            Opts::new(
+                configuration.process_resident_memory_bytes_name.clone(),
+                configuration.process_resident_memory_bytes_help.clone(),
+            )
+            .const_labels(configuration.const_labels.clone()),
+        )
+        .unwrap();
+
+        let virtual_memory_bytes = IntGauge::with_opts(
+            Opts::new(
+                configuration.process_virtual_memory_bytes_name.clone(),
+                configuration.process_virtual_memory_bytes_help.clone(),
+            )
+            .const_labels(configuration.const_labels.clone()),
+        )
+        .unwrap();
+
+        let cpu_usage_percent = Gauge::with_opts(
+            Opts::new(
+                configuration.process_cpu_usage_percent_name.clone(),
+                configuration.process_cpu_usage_percent_help.clone(),
+            )
+            .const_labels(configuration.const_labels.clone()),
+        )
+        .unwrap();
+
+        let open_fds = IntGauge::with_opts(
+            Opts::new(
+                configuration.process_open_fds_name.clone(),
+                configuration.process_open_fds_help.clone(),
+            )
+            .const_labels(configuration.const_labels.clone()),
+        )
+        .unwrap();
+
+        let uptime_seconds = IntGauge::with_opts(
+            Opts::new(
+                configuration.process_uptime_seconds_name.clone(),
+                configuration.process_uptime_seconds_help.clone(),
+            )
+            .const_labels(configuration.const_labels.clone()),
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(resident_memory_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(virtual_memory_bytes.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(cpu_usage_percent.clone()))
+            .unwrap();
+        registry.register(Box::new(open_fds.clone())).unwrap();
+        registry.register(Box::new(uptime_seconds.clone())).unwrap();
+
+        Self {
+            system: Mutex::new(System::new()),
+            pid,
+            refresh_interval,
+            last_refresh: Mutex::new(None),
+            resident_memory_bytes,
+            virtual_memory_bytes,
+            cpu_usage_percent,
+            open_fds,
+            uptime_seconds,
+        }
+    }
+
+    /// Refreshes the gauges, unless `refresh_interval` has not elapsed
+    /// since the last refresh.
+    pub fn refresh(&self) {
+        let mut last_refresh = self
+            .last_refresh
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(last_refresh) = *last_refresh {
+            if last_refresh.elapsed() < self.refresh_interval {
+                return;
+            }
+        }
+
+        let mut system = self
+            .system
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        system.refresh_process(self.pid);
+
+        if let Some(process) = system.process(self.pid) {
+            self.resident_memory_bytes.set(process.memory() as i64);
+            self.virtual_memory_bytes
+                .set(process.virtual_memory() as i64);
+            self.cpu_usage_percent.set(process.cpu_usage() as f64);
+            self.uptime_seconds.set(process.run_time() as i64);
+        }
+
+        self.open_fds.set(open_file_descriptor_count());
+
+        *last_refresh = Some(Instant::now());
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_file_descriptor_count() -> i64 {
+    std::fs::read_dir("/proc/self/fd")
+        .map(|entries| entries.count() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_file_descriptor_count() -> i64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh_throttles_within_refresh_interval() {
+        let registry = Registry::new();
+        let configuration = MetricsConfiguration::default();
+        let metrics = ProcessMetrics::new(&registry, &configuration, Duration::from_secs(3600));
+
+        metrics.refresh();
+        let first = metrics.resident_memory_bytes.get();
+
+        metrics.refresh();
+        let second = metrics.resident_memory_bytes.get();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn refresh_recovers_from_a_poisoned_mutex() {
+        let registry = Registry::new();
+        let configuration = MetricsConfiguration::default();
+        let metrics = ProcessMetrics::new(&registry, &configuration, Duration::ZERO);
+
+        let poisoned = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = metrics.system.lock().unwrap();
+            panic!("simulate a panic while holding the system mutex");
+        }));
+        assert!(poisoned.is_err());
+
+        metrics.refresh();
+    }
+}